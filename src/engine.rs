@@ -0,0 +1,859 @@
+use crate::rig_profile::{self, RigProfile, SampleEncoding};
+use crate::transport::{CipherTransport, DuplexLink, Transport};
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
+use rodio::{buffer::SamplesBuffer, OutputStream, Sink};
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+// Number of samples pulled per playback frame.
+const FRAME_LEN: usize = 500;
+// Ring buffer capacity, in samples. Generous enough to absorb serial jitter
+// without letting RX audio build up a noticeable lag.
+const RING_CAPACITY: usize = FRAME_LEN * 32;
+// Default VOX trip level, as an RMS fraction of full scale.
+const DEFAULT_TX_THRESHOLD: f32 = 0.05;
+// Default VOX hang time: how long TX stays keyed after the level last
+// crossed the threshold.
+const DEFAULT_TX_HANG_TIME: Duration = Duration::from_millis(300);
+
+/// Which physical link carries the CAT/audio byte stream to the radio.
+pub enum TransportKind {
+    Serial { port: String, baud_rate: u32 },
+    Tcp { addr: String },
+}
+
+impl Default for TransportKind {
+    fn default() -> Self {
+        TransportKind::Serial {
+            port: "/dev/ttyUSB0".to_string(),
+            baud_rate: 115200,
+        }
+    }
+}
+
+/// Everything [`Engine::start`] needs to bring the audio/CAT pipeline up.
+pub struct EngineConfig {
+    pub transport: TransportKind,
+    /// Symmetric XOR keystream key; `None` runs the link in plaintext.
+    pub cipher_key: Option<String>,
+    /// Name understood by [`rig_profile::select_profile`] (e.g. "trusdx").
+    pub rig_profile: String,
+    /// RMS level (fraction of full scale) the captured audio must cross to
+    /// key the transmitter.
+    pub tx_threshold: f32,
+    /// How long TX stays keyed after the level last crossed `tx_threshold`,
+    /// so brief gaps between words don't chop into RX/TX.
+    pub tx_hang_time: Duration,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            transport: TransportKind::default(),
+            cipher_key: None,
+            rig_profile: "trusdx".to_string(),
+            tx_threshold: DEFAULT_TX_THRESHOLD,
+            tx_hang_time: DEFAULT_TX_HANG_TIME,
+        }
+    }
+}
+
+// Shared state queried by EngineHandle.
+struct SharedState {
+    underrun_counter: usize,
+    tx_status: bool,
+    input_rate: u32,
+    output_rate: u32,
+    // Current measured VOX level (RMS, fraction of full scale), for metering.
+    tx_level: f32,
+}
+
+// RMS level of a normalized `[-1.0, 1.0]` frame, as a fraction of full
+// scale.
+fn measure_level(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let sum_sq: f64 = samples.iter().map(|&s| (s * s) as f64).sum();
+    (sum_sq / samples.len() as f64).sqrt() as f32
+}
+
+// Producer half of the RX ring buffer. Lives on the serial-read thread.
+struct AudioProducer {
+    producer: HeapProducer<f32>,
+    buffer_len: Arc<AtomicUsize>,
+    overflow_counter: Arc<AtomicUsize>,
+    encoding: SampleEncoding,
+    // Bytes read but short of a full sample (possible whenever
+    // `encoding.bytes_per_sample() > 1` and a read lands mid-sample);
+    // prepended to the next call instead of being dropped.
+    pending: Vec<u8>,
+}
+
+impl AudioProducer {
+    // Converts incoming serial bytes to samples once, at ingest, instead of
+    // re-decoding them on every playback frame.
+    fn produce_bytes(&mut self, bytes: &[u8]) {
+        self.pending.extend_from_slice(bytes);
+        let chunk_size = self.encoding.bytes_per_sample();
+        let usable_len = self.pending.len() - (self.pending.len() % chunk_size);
+        for sample in self.encoding.decode(&self.pending[..usable_len]) {
+            // The ring is sized to absorb serial jitter (see RING_CAPACITY),
+            // so a full ring means the playback side has fallen behind, not
+            // a transient hiccup; count it instead of dropping the sample
+            // unnoticed.
+            if self.producer.push(sample).is_err() {
+                self.overflow_counter.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.pending.drain(..usable_len);
+        self.buffer_len.store(self.producer.len(), Ordering::Relaxed);
+    }
+}
+
+// Consumer half of the RX ring buffer. Lives on the playback thread.
+struct AudioConsumer {
+    consumer: HeapConsumer<f32>,
+    buffer_len: Arc<AtomicUsize>,
+}
+
+impl AudioConsumer {
+    // Fills `out` from the ring buffer. Returns `false` without touching
+    // `out` if fewer samples than requested are available, so the caller can
+    // emit silence instead of panicking on a short read.
+    fn consume_exact(&mut self, out: &mut [f32]) -> bool {
+        if self.consumer.len() < out.len() {
+            return false;
+        }
+        for slot in out.iter_mut() {
+            *slot = self.consumer.pop().unwrap_or(0.0);
+        }
+        self.buffer_len.store(self.consumer.len(), Ordering::Relaxed);
+        true
+    }
+}
+
+// Linear-interpolation resampler. Good enough to bridge whatever the sound
+// card actually opened at to the rig's fixed rate; revisit with a
+// band-limited (e.g. sinc) resampler if the aliasing becomes audible.
+fn resample(input: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if from_rate == to_rate || input.is_empty() {
+        return input.to_vec();
+    }
+    let ratio = to_rate as f64 / from_rate as f64;
+    let out_len = ((input.len() as f64) * ratio).round() as usize;
+    let mut out = Vec::with_capacity(out_len);
+    for i in 0..out_len {
+        let src_pos = i as f64 / ratio;
+        let idx = src_pos.floor() as usize;
+        let frac = (src_pos - idx as f64) as f32;
+        let a = input.get(idx).copied().unwrap_or(0.0);
+        let b = input.get(idx + 1).copied().unwrap_or(a);
+        out.push(a + (b - a) * frac);
+    }
+    out
+}
+
+// Sample formats this engine knows how to read/write, ranked best (no
+// conversion needed) to worst.
+const SUPPORTED_SAMPLE_FORMATS: [cpal::SampleFormat; 3] = [
+    cpal::SampleFormat::F32,
+    cpal::SampleFormat::I16,
+    cpal::SampleFormat::U16,
+];
+
+// Picks the closest-to-ideal config of one sample format from whatever a
+// device actually supports: a mono range at `desired_rate` if one exists,
+// otherwise the best available range in that format, preferring mono over
+// multi-channel and an in-range rate over a clamped one. `None` if the
+// device offers no range in this format at all.
+fn best_config_for_format(
+    configs: &[cpal::SupportedStreamConfigRange],
+    format: cpal::SampleFormat,
+    desired_rate: u32,
+) -> Option<cpal::SupportedStreamConfig> {
+    let in_range = |cfg: &cpal::SupportedStreamConfigRange| {
+        cfg.min_sample_rate().0 <= desired_rate && desired_rate <= cfg.max_sample_rate().0
+    };
+    let mut best: Option<cpal::SupportedStreamConfigRange> = None;
+    for &cfg in configs.iter().filter(|cfg| cfg.sample_format() == format) {
+        if cfg.channels() == 1 && in_range(&cfg) {
+            return Some(cfg.with_sample_rate(cpal::SampleRate(desired_rate)));
+        }
+        let replace = match &best {
+            None => true,
+            Some(b) if cfg.channels() == 1 && b.channels() != 1 => true,
+            Some(b) if cfg.channels() != 1 && b.channels() == 1 => false,
+            Some(b) => in_range(&cfg) && !in_range(b),
+        };
+        if replace {
+            best = Some(cfg);
+        }
+    }
+    best.map(|cfg| {
+        let clamped_rate = desired_rate.clamp(cfg.min_sample_rate().0, cfg.max_sample_rate().0);
+        cfg.with_sample_rate(cpal::SampleRate(clamped_rate))
+    })
+}
+
+// Picks the closest-to-ideal config from whatever a device actually
+// supports, across sample formats: F32 preferred since it needs no
+// conversion downstream, falling back to I16 then U16 - plenty of hardware,
+// especially onboard/built-in chips, only ever offers integer PCM, and
+// refusing to negotiate with it reintroduces the same "works on my machine
+// only" failure this function was written to stop, just on the format axis
+// instead of the rate one. Callers downmix if the result isn't mono and
+// convert to f32 if the result isn't F32.
+fn negotiate_config(
+    configs: Vec<cpal::SupportedStreamConfigRange>,
+    desired_rate: u32,
+) -> cpal::SupportedStreamConfig {
+    SUPPORTED_SAMPLE_FORMATS
+        .into_iter()
+        .find_map(|format| best_config_for_format(&configs, format, desired_rate))
+        .expect("device offers no F32, I16, or U16 stream config")
+}
+
+// Averages an interleaved multi-channel frame down to mono. A no-op copy
+// when `channels == 1`.
+fn downmix_to_mono(data: &[f32], channels: usize) -> Vec<f32> {
+    if channels <= 1 {
+        return data.to_vec();
+    }
+    data.chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect()
+}
+
+// Negotiated input stream shape plus the rig parameters its callback needs
+// to resample/encode captured audio, bundled so `build_tx_input_stream`
+// doesn't carry them as a wall of individual arguments.
+struct TxInputStreamConfig {
+    sample_format: cpal::SampleFormat,
+    channels: usize,
+    input_rate: u32,
+    tx_rate: u32,
+    tx_encoding: SampleEncoding,
+}
+
+// Builds the cpal input stream for whatever PCM format `negotiate_input_config`
+// picked, normalizing every format to downmixed f32 before resampling to the
+// rig's TX rate and handing it to `tx_encoding` for the wire. Typed
+// separately per format because `build_input_stream` is generic over the
+// sample type rather than dynamic over `SampleFormat`.
+fn build_tx_input_stream(
+    input_device: &cpal::Device,
+    input_config: &cpal::StreamConfig,
+    config: TxInputStreamConfig,
+    input_data: Arc<Mutex<Vec<u8>>>,
+) -> cpal::Stream {
+    fn on_error(err: cpal::StreamError) {
+        eprintln!("An error occurred on input stream: {}", err);
+    }
+
+    let TxInputStreamConfig {
+        sample_format,
+        channels,
+        input_rate,
+        tx_rate,
+        tx_encoding,
+    } = config;
+
+    match sample_format {
+        cpal::SampleFormat::F32 => input_device
+            .build_input_stream(
+                input_config,
+                move |data: &[f32], _: &cpal::InputCallbackInfo| {
+                    let mono = downmix_to_mono(data, channels);
+                    let resampled = resample(&mono, input_rate, tx_rate);
+                    input_data.lock().unwrap().extend(tx_encoding.encode(&resampled));
+                },
+                on_error,
+                None,
+            )
+            .unwrap(),
+        cpal::SampleFormat::I16 => input_device
+            .build_input_stream(
+                input_config,
+                move |data: &[i16], _: &cpal::InputCallbackInfo| {
+                    let as_f32: Vec<f32> = data.iter().map(|&s| s as f32 / i16::MAX as f32).collect();
+                    let mono = downmix_to_mono(&as_f32, channels);
+                    let resampled = resample(&mono, input_rate, tx_rate);
+                    input_data.lock().unwrap().extend(tx_encoding.encode(&resampled));
+                },
+                on_error,
+                None,
+            )
+            .unwrap(),
+        cpal::SampleFormat::U16 => input_device
+            .build_input_stream(
+                input_config,
+                move |data: &[u16], _: &cpal::InputCallbackInfo| {
+                    // U16 centers on 32768, not 0 (see cpal::SampleFormat::U16's doc).
+                    let as_f32: Vec<f32> = data.iter().map(|&s| (s as f32 - 32768.0) / 32768.0).collect();
+                    let mono = downmix_to_mono(&as_f32, channels);
+                    let resampled = resample(&mono, input_rate, tx_rate);
+                    input_data.lock().unwrap().extend(tx_encoding.encode(&resampled));
+                },
+                on_error,
+                None,
+            )
+            .unwrap(),
+        other => panic!("negotiated an unsupported input sample format: {:?}", other),
+    }
+}
+
+fn negotiate_input_config(device: &cpal::Device, desired_rate: u32) -> cpal::SupportedStreamConfig {
+    let configs: Vec<_> = device
+        .supported_input_configs()
+        .expect("failed to query input configs")
+        .collect();
+    negotiate_config(configs, desired_rate)
+}
+
+fn negotiate_output_config(device: &cpal::Device, desired_rate: u32) -> cpal::SupportedStreamConfig {
+    let configs: Vec<_> = device
+        .supported_output_configs()
+        .expect("failed to query output configs")
+        .collect();
+    negotiate_config(configs, desired_rate)
+}
+
+fn build_link(transport: &TransportKind, cipher_key: &Option<String>) -> Box<dyn DuplexLink> {
+    let transport = match transport {
+        TransportKind::Tcp { addr } => {
+            let stream = TcpStream::connect(addr).expect("Failed to connect to head unit");
+            // Without a read timeout, a `read` that's waiting on an idle
+            // link never returns, so `receive_serial_audio`'s shutdown
+            // check is never reached and `EngineHandle::shutdown` hangs
+            // joining that thread.
+            stream
+                .set_read_timeout(Some(Duration::from_millis(10)))
+                .expect("Failed to set TCP read timeout");
+            Transport::Tcp(stream)
+        }
+        TransportKind::Serial { port, baud_rate } => {
+            let serport = serialport::new(port.as_str(), *baud_rate)
+                .timeout(Duration::from_millis(10))
+                .open()
+                .expect("Failed to open serial port");
+            Transport::Serial(serport)
+        }
+    };
+
+    match cipher_key {
+        Some(key) if !key.is_empty() => {
+            Box::new(CipherTransport::new(transport, key.as_bytes().to_vec()))
+        }
+        _ => Box::new(transport),
+    }
+}
+
+fn receive_serial_audio(
+    mut link: Box<dyn DuplexLink>,
+    mut producer: AudioProducer,
+    shutdown: Arc<AtomicBool>,
+) {
+    let mut buffer = [0u8; 500];
+    while !shutdown.load(Ordering::Relaxed) {
+        match link.read(&mut buffer) {
+            Ok(bytes_read) => {
+                producer.produce_bytes(&buffer[..bytes_read]);
+            }
+            Err(ref e)
+                if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) =>
+            {
+                // No data yet, just wait. A read timeout surfaces as
+                // WouldBlock or TimedOut depending on the platform and on
+                // whether the link is serial or TCP.
+                thread::sleep(Duration::from_millis(10));
+            }
+            Err(e) => {
+                eprintln!("Serial read error: {:?}", e);
+                break;
+            }
+        }
+    }
+}
+
+fn play_receive_audio(
+    state: Arc<Mutex<SharedState>>,
+    mut consumer: AudioConsumer,
+    sink: Sink,
+    rig_rx_rate: u32,
+    shutdown: Arc<AtomicBool>,
+) {
+    let mut frame = [0f32; FRAME_LEN];
+    let output_rate = state.lock().unwrap().output_rate;
+    while !shutdown.load(Ordering::Relaxed) {
+        let tx_status = state.lock().unwrap().tx_status;
+        if consumer.consume_exact(&mut frame) {
+            // While we're transmitting, the radio's own TX audio is what's
+            // coming back over the RX path - drain it without playing it so
+            // it doesn't loop back into the speaker.
+            if !tx_status {
+                let resampled = resample(&frame, rig_rx_rate, output_rate);
+                sink.append(SamplesBuffer::new(1, output_rate, resampled));
+            }
+        } else if !tx_status {
+            let mut state = state.lock().unwrap();
+            println!("UNDERRUN #{} - emitting silence", state.underrun_counter);
+            state.underrun_counter += 1;
+            drop(state);
+            let silence = resample(&[0f32; FRAME_LEN], rig_rx_rate, output_rate);
+            sink.append(SamplesBuffer::new(1, output_rate, silence));
+            thread::sleep(Duration::from_millis(10));
+        } else {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+// VOX trip level/hang time plus the manual-PTT and shutdown signals
+// `transmit_audio_via_serial` needs alongside the audio/link state, bundled
+// so the function doesn't carry them as a wall of individual arguments.
+struct TxKeying {
+    ptt_forced: Arc<AtomicBool>,
+    tx_threshold: f32,
+    tx_hang_time: Duration,
+    shutdown: Arc<AtomicBool>,
+}
+
+fn transmit_audio_via_serial(
+    input_data: Arc<Mutex<Vec<u8>>>,
+    mut link: Box<dyn DuplexLink>,
+    state: Arc<Mutex<SharedState>>,
+    profile: Arc<dyn RigProfile>,
+    keying: TxKeying,
+) {
+    // Tracks the last time the level crossed `tx_threshold`, so TX stays
+    // keyed through brief gaps instead of chopping on every dip.
+    let mut last_loud: Option<Instant> = None;
+
+    while !keying.shutdown.load(Ordering::Relaxed) {
+        // Lock just long enough to snapshot and drain the captured audio,
+        // instead of holding the mutex for the thread's whole lifetime.
+        let buffer = {
+            let mut input_data = input_data.lock().unwrap();
+            std::mem::take(&mut *input_data)
+        };
+
+        let level = measure_level(&profile.sample_encoding().decode(&buffer));
+        if level >= keying.tx_threshold {
+            last_loud = Some(Instant::now());
+        }
+        let within_hang_time = last_loud.is_some_and(|t| t.elapsed() < keying.tx_hang_time);
+        let keyed = within_hang_time || keying.ptt_forced.load(Ordering::Relaxed);
+
+        let mut state = state.lock().unwrap();
+        state.tx_level = level;
+        if keyed {
+            if !state.tx_status {
+                state.tx_status = true;
+                println!("TX ON");
+                if let Err(e) = link.write_all(profile.tx_on()) {
+                    eprintln!("Serial write error (tx_on): {:?}", e);
+                    state.tx_status = false;
+                    break;
+                }
+            }
+            if let Err(e) = link.write_all(&buffer) {
+                eprintln!("Serial write error (tx audio): {:?}", e);
+                // Best-effort un-key: don't leave the radio stuck keyed with
+                // no thread left alive to send rx().
+                let _ = link.write_all(profile.rx());
+                state.tx_status = false;
+                break;
+            }
+        } else if state.tx_status {
+            if let Err(e) = link.write_all(profile.rx()) {
+                eprintln!("Serial write error (rx): {:?}", e);
+                state.tx_status = false;
+                break;
+            }
+            state.tx_status = false;
+            println!("TX OFF");
+        }
+        drop(state);
+
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// A running audio/CAT pipeline. Dropping the config after [`Engine::start`]
+/// is fine; call [`EngineHandle::shutdown`] to stop the worker threads.
+pub struct EngineHandle {
+    state: Arc<Mutex<SharedState>>,
+    buffer_len: Arc<AtomicUsize>,
+    overflow_counter: Arc<AtomicUsize>,
+    ptt_forced: Arc<AtomicBool>,
+    shutdown: Arc<AtomicBool>,
+    threads: Vec<thread::JoinHandle<()>>,
+    // Kept alive for the lifetime of the engine; dropping either stops audio.
+    _input_stream: cpal::Stream,
+    _output_stream: OutputStream,
+}
+
+impl EngineHandle {
+    /// Number of RX samples currently sitting in the ring buffer.
+    pub fn buffer_len(&self) -> usize {
+        self.buffer_len.load(Ordering::Relaxed)
+    }
+
+    /// Total RX underruns (ring buffer ran dry) since startup.
+    pub fn underrun_count(&self) -> usize {
+        self.state.lock().unwrap().underrun_counter
+    }
+
+    /// Total RX samples dropped because the ring buffer was full (playback
+    /// side falling behind the serial-read side) since startup.
+    pub fn overflow_count(&self) -> usize {
+        self.overflow_counter.load(Ordering::Relaxed)
+    }
+
+    /// Whether the transmitter is currently keyed.
+    pub fn tx_status(&self) -> bool {
+        self.state.lock().unwrap().tx_status
+    }
+
+    /// Current measured VOX level (RMS, fraction of full scale), for a
+    /// level meter.
+    pub fn tx_level(&self) -> f32 {
+        self.state.lock().unwrap().tx_level
+    }
+
+    /// Negotiated input/output sample rates, in Hz.
+    pub fn sample_rates(&self) -> (u32, u32) {
+        let state = self.state.lock().unwrap();
+        (state.input_rate, state.output_rate)
+    }
+
+    /// Forces (or releases) the transmitter independent of the VOX/level
+    /// trip, for a manual push-to-talk control.
+    pub fn ptt(&self, on: bool) {
+        self.ptt_forced.store(on, Ordering::Relaxed);
+    }
+
+    /// Signals the worker threads to stop and waits for them to exit.
+    /// Returns a description of each thread that panicked rather than
+    /// exiting cleanly, so a dead RX/TX worker isn't invisible to the
+    /// caller - an empty vec means every thread shut down normally.
+    pub fn shutdown(self) -> Vec<String> {
+        self.shutdown.store(true, Ordering::Relaxed);
+        let mut panics = Vec::new();
+        for handle in self.threads {
+            if let Err(payload) = handle.join() {
+                let msg = payload
+                    .downcast_ref::<&str>()
+                    .map(|s| s.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "worker thread panicked".to_string());
+                eprintln!("Worker thread panicked during shutdown: {}", msg);
+                panics.push(msg);
+            }
+        }
+        panics
+    }
+}
+
+pub struct Engine;
+
+impl Engine {
+    /// Negotiates audio devices, opens the configured link, and starts the
+    /// RX/TX worker threads.
+    pub fn start(config: EngineConfig) -> EngineHandle {
+        let profile: Arc<dyn RigProfile> = Arc::from(rig_profile::select_profile(&config.rig_profile));
+        let mut link = build_link(&config.transport, &config.cipher_key);
+
+        let host = cpal::default_host();
+        let input_device = host.default_input_device().expect("No input device available");
+        let input_supported_config = negotiate_input_config(&input_device, profile.tx_rate());
+        let input_format = input_supported_config.sample_format();
+        let input_rate = input_supported_config.sample_rate().0;
+        let input_channels = input_supported_config.channels() as usize;
+        let input_config: cpal::StreamConfig = input_supported_config.into();
+
+        let output_device = host.default_output_device().expect("No output device available");
+        let output_supported_config = negotiate_output_config(&output_device, profile.rx_rate());
+        let output_rate = output_supported_config.sample_rate().0;
+        let (output_stream, stream_handle) =
+            OutputStream::try_from_device_config(&output_device, output_supported_config)
+                .expect("failed to open negotiated output stream");
+        let sink = Sink::try_new(&stream_handle).unwrap();
+
+        let state = Arc::new(Mutex::new(SharedState {
+            underrun_counter: 0,
+            tx_status: false,
+            input_rate,
+            output_rate,
+            tx_level: 0.0,
+        }));
+        let buffer_len = Arc::new(AtomicUsize::new(0));
+        let overflow_counter = Arc::new(AtomicUsize::new(0));
+        let ptt_forced = Arc::new(AtomicBool::new(false));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let rx_ring = HeapRb::<f32>::new(RING_CAPACITY);
+        let (rx_producer, rx_consumer) = rx_ring.split();
+        let rx_producer = AudioProducer {
+            producer: rx_producer,
+            buffer_len: Arc::clone(&buffer_len),
+            overflow_counter: Arc::clone(&overflow_counter),
+            encoding: profile.sample_encoding(),
+            pending: Vec::new(),
+        };
+        let rx_consumer = AudioConsumer {
+            consumer: rx_consumer,
+            buffer_len: Arc::clone(&buffer_len),
+        };
+
+        // Buffer to store input data, already resampled and re-quantized to
+        // the rig's expected TX rate and wire encoding.
+        let input_data: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(Vec::new()));
+
+        let input_data_clone = input_data.clone();
+        let tx_rate = profile.tx_rate();
+        let tx_encoding = profile.sample_encoding();
+        let input_stream = build_tx_input_stream(
+            &input_device,
+            &input_config,
+            TxInputStreamConfig {
+                sample_format: input_format,
+                channels: input_channels,
+                input_rate,
+                tx_rate,
+                tx_encoding,
+            },
+            input_data_clone,
+        );
+        input_stream.play().unwrap();
+
+        // Wait for device to start after opening the link
+        thread::sleep(Duration::from_secs(3));
+        link.write_all(profile.enable_audio_stream()).unwrap();
+
+        let state_tx = Arc::clone(&state);
+        let state_play = Arc::clone(&state);
+        let link_clone = link.try_clone_link().expect("Failed to clone link");
+        let rig_rx_rate = profile.rx_rate();
+        let profile_tx = Arc::clone(&profile);
+        let shutdown_rx = Arc::clone(&shutdown);
+        let shutdown_play = Arc::clone(&shutdown);
+        let shutdown_tx = Arc::clone(&shutdown);
+        let ptt_forced_tx = Arc::clone(&ptt_forced);
+
+        let mut threads = Vec::new();
+        threads.push(thread::spawn(move || {
+            receive_serial_audio(link_clone, rx_producer, shutdown_rx)
+        }));
+        threads.push(thread::spawn(move || {
+            play_receive_audio(state_play, rx_consumer, sink, rig_rx_rate, shutdown_play)
+        }));
+        let keying = TxKeying {
+            ptt_forced: ptt_forced_tx,
+            tx_threshold: config.tx_threshold,
+            tx_hang_time: config.tx_hang_time,
+            shutdown: shutdown_tx,
+        };
+        threads.push(thread::spawn(move || {
+            transmit_audio_via_serial(input_data, link, state_tx, profile_tx, keying)
+        }));
+
+        EngineHandle {
+            state,
+            buffer_len,
+            overflow_counter,
+            ptt_forced,
+            shutdown,
+            threads,
+            _input_stream: input_stream,
+            _output_stream: output_stream,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpal::{SampleFormat, SampleRate, SupportedBufferSize, SupportedStreamConfigRange};
+
+    #[test]
+    fn resample_is_a_no_op_at_equal_rates() {
+        let input = vec![0.1, -0.2, 0.3];
+        assert_eq!(resample(&input, 8000, 8000), input);
+    }
+
+    #[test]
+    fn resample_of_empty_input_is_empty() {
+        assert!(resample(&[], 8000, 16000).is_empty());
+    }
+
+    #[test]
+    fn resample_upsamples_to_the_expected_length() {
+        let input = vec![0.0, 1.0, 0.0, -1.0];
+        let out = resample(&input, 8000, 16000);
+        assert_eq!(out.len(), 8);
+    }
+
+    #[test]
+    fn resample_downsamples_to_the_expected_length() {
+        let input = vec![0.0; 16000];
+        let out = resample(&input, 16000, 8000);
+        assert_eq!(out.len(), 8000);
+    }
+
+    fn mono_f32_range(min_rate: u32, max_rate: u32) -> SupportedStreamConfigRange {
+        SupportedStreamConfigRange::new(
+            1,
+            SampleRate(min_rate),
+            SampleRate(max_rate),
+            SupportedBufferSize::Unknown,
+            SampleFormat::F32,
+        )
+    }
+
+    fn stereo_f32_range(min_rate: u32, max_rate: u32) -> SupportedStreamConfigRange {
+        SupportedStreamConfigRange::new(
+            2,
+            SampleRate(min_rate),
+            SampleRate(max_rate),
+            SupportedBufferSize::Unknown,
+            SampleFormat::F32,
+        )
+    }
+
+    fn mono_i16_range(min_rate: u32, max_rate: u32) -> SupportedStreamConfigRange {
+        SupportedStreamConfigRange::new(
+            1,
+            SampleRate(min_rate),
+            SampleRate(max_rate),
+            SupportedBufferSize::Unknown,
+            SampleFormat::I16,
+        )
+    }
+
+    #[test]
+    fn negotiate_config_picks_exact_mono_rate_when_offered() {
+        let configs = vec![stereo_f32_range(8000, 48000), mono_f32_range(8000, 48000)];
+        let cfg = negotiate_config(configs, 11525);
+        assert_eq!(cfg.channels(), 1);
+        assert_eq!(cfg.sample_rate().0, 11525);
+    }
+
+    #[test]
+    fn negotiate_config_falls_back_to_stereo_instead_of_panicking() {
+        let configs = vec![stereo_f32_range(8000, 48000)];
+        let cfg = negotiate_config(configs, 11525);
+        assert_eq!(cfg.channels(), 2);
+        assert_eq!(cfg.sample_rate().0, 11525);
+    }
+
+    #[test]
+    fn negotiate_config_clamps_to_the_nearest_offered_rate() {
+        let configs = vec![mono_f32_range(16000, 48000)];
+        let cfg = negotiate_config(configs, 8000);
+        assert_eq!(cfg.sample_rate().0, 16000);
+    }
+
+    #[test]
+    fn negotiate_config_falls_back_to_i16_instead_of_panicking_when_no_f32_is_offered() {
+        let configs = vec![mono_i16_range(8000, 48000)];
+        let cfg = negotiate_config(configs, 11525);
+        assert_eq!(cfg.sample_format(), SampleFormat::I16);
+        assert_eq!(cfg.sample_rate().0, 11525);
+    }
+
+    #[test]
+    fn negotiate_config_prefers_f32_over_i16_when_both_are_offered() {
+        let configs = vec![mono_i16_range(8000, 48000), mono_f32_range(8000, 48000)];
+        let cfg = negotiate_config(configs, 11525);
+        assert_eq!(cfg.sample_format(), SampleFormat::F32);
+    }
+
+    #[test]
+    fn downmix_to_mono_averages_interleaved_channels() {
+        let stereo = [1.0, -1.0, 0.5, 0.5];
+        assert_eq!(downmix_to_mono(&stereo, 2), vec![0.0, 0.5]);
+    }
+
+    #[test]
+    fn downmix_to_mono_is_a_no_op_for_mono_input() {
+        let mono = [0.1, 0.2, 0.3];
+        assert_eq!(downmix_to_mono(&mono, 1), mono.to_vec());
+    }
+
+    #[test]
+    fn consume_exact_returns_false_and_leaves_out_untouched_on_a_short_read() {
+        let rb = HeapRb::<f32>::new(4);
+        let (mut producer, consumer) = rb.split();
+        producer.push(1.0).unwrap();
+        let mut consumer = AudioConsumer {
+            consumer,
+            buffer_len: Arc::new(AtomicUsize::new(0)),
+        };
+
+        let mut out = [9.0; 2];
+        assert!(!consumer.consume_exact(&mut out));
+        assert_eq!(out, [9.0, 9.0]);
+    }
+
+    #[test]
+    fn consume_exact_drains_the_ring_buffer_in_order() {
+        let rb = HeapRb::<f32>::new(4);
+        let (mut producer, consumer) = rb.split();
+        producer.push(1.0).unwrap();
+        producer.push(2.0).unwrap();
+        let mut consumer = AudioConsumer {
+            consumer,
+            buffer_len: Arc::new(AtomicUsize::new(0)),
+        };
+
+        let mut out = [0.0; 2];
+        assert!(consumer.consume_exact(&mut out));
+        assert_eq!(out, [1.0, 2.0]);
+    }
+
+    #[test]
+    fn produce_bytes_holds_a_trailing_partial_sample_for_i16_encoding() {
+        let rb = HeapRb::<f32>::new(4);
+        let (producer, _consumer) = rb.split();
+        let mut producer = AudioProducer {
+            producer,
+            buffer_len: Arc::new(AtomicUsize::new(0)),
+            overflow_counter: Arc::new(AtomicUsize::new(0)),
+            encoding: SampleEncoding::I16,
+            pending: Vec::new(),
+        };
+
+        // Three bytes: one full little-endian i16 sample plus a lone
+        // trailing byte split across this call and the next.
+        producer.produce_bytes(&[0x00, 0x40, 0xff]);
+        assert_eq!(producer.pending, vec![0xff]);
+
+        producer.produce_bytes(&[0x7f]);
+        assert!(producer.pending.is_empty());
+    }
+
+    #[test]
+    fn produce_bytes_counts_drops_once_the_ring_is_full() {
+        let rb = HeapRb::<f32>::new(2);
+        let (producer, _consumer) = rb.split();
+        let overflow_counter = Arc::new(AtomicUsize::new(0));
+        let mut producer = AudioProducer {
+            producer,
+            buffer_len: Arc::new(AtomicUsize::new(0)),
+            overflow_counter: Arc::clone(&overflow_counter),
+            encoding: SampleEncoding::U8,
+            pending: Vec::new(),
+        };
+
+        // Ring holds 2 samples; the third and fourth have nowhere to go.
+        producer.produce_bytes(&[0x80, 0x80, 0x80, 0x80]);
+        assert_eq!(overflow_counter.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,13 @@
+//! Core engine for talking to a (tr)uSDX-style transceiver: negotiating
+//! audio devices, streaming RX/TX audio, and driving the CAT link. The
+//! `rustysdx` binary is a thin front-end over [`Engine`]; other front-ends
+//! (a GUI, an FFI layer, a different CLI) can depend on this crate directly
+//! instead of duplicating the threading code.
+
+pub mod rig_profile;
+pub mod transport;
+
+mod engine;
+
+pub use engine::{Engine, EngineConfig, EngineHandle, TransportKind};
+pub use transport::run_head_unit;
@@ -0,0 +1,168 @@
+//! CAT-command and audio-format profiles for the transceivers this program
+//! can drive. Centralizing the command byte-strings here means
+//! `transmit_audio_via_serial` and the startup handshake never hard-code a
+//! single firmware's dialect.
+
+/// Wire encoding of the PCM samples a rig streams/expects over serial.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleEncoding {
+    U8,
+    I16,
+}
+
+impl SampleEncoding {
+    /// Wire bytes per sample.
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            SampleEncoding::U8 => 1,
+            SampleEncoding::I16 => 2,
+        }
+    }
+
+    /// Decodes wire bytes into normalized `[-1.0, 1.0]` samples. Any
+    /// trailing bytes short of a full sample are ignored; callers reading
+    /// off a byte stream in arbitrary chunks should hold those back and
+    /// prepend them to the next call instead of dropping them.
+    pub fn decode(self, bytes: &[u8]) -> Vec<f32> {
+        match self {
+            // 127, not 128: matches `encode`'s scale so 1 and 255 (the
+            // extremes `encode` actually produces) round-trip back to
+            // exactly -1.0/1.0 instead of falling a code short.
+            SampleEncoding::U8 => bytes.iter().map(|&b| (b as f32 - 128.0) / 127.0).collect(),
+            SampleEncoding::I16 => bytes
+                .chunks_exact(2)
+                .map(|c| i16::from_le_bytes([c[0], c[1]]) as f32 / i16::MAX as f32)
+                .collect(),
+        }
+    }
+
+    /// Encodes normalized `[-1.0, 1.0]` samples into wire bytes.
+    pub fn encode(self, samples: &[f32]) -> Vec<u8> {
+        match self {
+            // Symmetric scale around the 128 bias: 127 codes on each side
+            // of center, so +-1.0 both land on a code `decode` recovers
+            // exactly instead of +1.0 alone landing a code short (the old
+            // `* 128.0` scale only had 127 codes above center but 128
+            // below, so saturating to 255 lost an extra quantization step
+            // on the loudest positive samples).
+            SampleEncoding::U8 => samples
+                .iter()
+                .map(|&s| (s.clamp(-1.0, 1.0) * 127.0 + 128.0) as u8)
+                .collect(),
+            SampleEncoding::I16 => samples
+                .iter()
+                .flat_map(|&s| ((s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes())
+                .collect(),
+        }
+    }
+}
+
+/// Describes one transceiver's CAT handshake and audio characteristics.
+pub trait RigProfile: Send + Sync {
+    /// Command that puts the rig into USB-audio-streaming mode, sent once at startup.
+    fn enable_audio_stream(&self) -> &'static [u8];
+    /// Command that keys the transmitter.
+    fn tx_on(&self) -> &'static [u8];
+    /// Command that drops back to receive.
+    fn rx(&self) -> &'static [u8];
+    /// Encoding of the samples this rig sends/accepts.
+    fn sample_encoding(&self) -> SampleEncoding;
+    /// Rate, in Hz, the rig expects microphone audio to be sent at.
+    fn tx_rate(&self) -> u32;
+    /// Rate, in Hz, the rig sends received audio at.
+    fn rx_rate(&self) -> u32;
+}
+
+/// (tr)uSDX firmware: 8-bit PCM over the CAT serial link, fixed rates.
+pub struct TruSdxProfile;
+
+impl RigProfile for TruSdxProfile {
+    fn enable_audio_stream(&self) -> &'static [u8] {
+        b"UA1;"
+    }
+
+    fn tx_on(&self) -> &'static [u8] {
+        b"UA1;TX0;"
+    }
+
+    fn rx(&self) -> &'static [u8] {
+        b";RX;"
+    }
+
+    fn sample_encoding(&self) -> SampleEncoding {
+        SampleEncoding::U8
+    }
+
+    fn tx_rate(&self) -> u32 {
+        11525
+    }
+
+    fn rx_rate(&self) -> u32 {
+        7812
+    }
+}
+
+/// Generic Kenwood-style CAT rig (e.g. TS-480/TS-2000 family). These don't
+/// need a USB-audio handshake command, and ship audio over a sound card at
+/// ordinary rates with 16-bit samples rather than the truSDX's 8-bit stream.
+pub struct KenwoodProfile;
+
+impl RigProfile for KenwoodProfile {
+    fn enable_audio_stream(&self) -> &'static [u8] {
+        b""
+    }
+
+    fn tx_on(&self) -> &'static [u8] {
+        b"TX;"
+    }
+
+    fn rx(&self) -> &'static [u8] {
+        b"RX;"
+    }
+
+    fn sample_encoding(&self) -> SampleEncoding {
+        SampleEncoding::I16
+    }
+
+    fn tx_rate(&self) -> u32 {
+        8000
+    }
+
+    fn rx_rate(&self) -> u32 {
+        8000
+    }
+}
+
+/// Selects a profile by name, falling back to the truSDX default for an
+/// unrecognized or unset name.
+pub fn select_profile(name: &str) -> Box<dyn RigProfile> {
+    match name {
+        "kenwood" => Box::new(KenwoodProfile),
+        _ => Box::new(TruSdxProfile),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn u8_round_trip_recovers_samples_within_quantization_error() {
+        let samples = vec![-1.0, -0.5, 0.0, 0.25, 1.0];
+        let decoded = SampleEncoding::U8.decode(&SampleEncoding::U8.encode(&samples));
+        assert_eq!(decoded.len(), samples.len());
+        for (original, recovered) in samples.iter().zip(decoded) {
+            assert!((original - recovered).abs() < 1.0 / 128.0);
+        }
+    }
+
+    #[test]
+    fn i16_round_trip_recovers_samples_within_quantization_error() {
+        let samples = vec![-1.0, -0.5, 0.0, 0.25, 1.0];
+        let decoded = SampleEncoding::I16.decode(&SampleEncoding::I16.encode(&samples));
+        assert_eq!(decoded.len(), samples.len());
+        for (original, recovered) in samples.iter().zip(decoded) {
+            assert!((original - recovered).abs() < 1.0 / i16::MAX as f32);
+        }
+    }
+}